@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use axum::extract::{FromRef, FromRequestParts};
+use axum::http::request::Parts;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::errors::AppError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    Viewer,
+    Controller,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct KeyConfig {
+    /// Human-readable label only, never matched against. Lets an operator
+    /// tell keys apart in the config file without leaking that info to logs.
+    pub name: String,
+    pub token: String,
+    pub scope: Scope,
+    pub not_before: Option<DateTime<Utc>>,
+    pub not_after: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct KeyStoreConfig {
+    #[serde(default)]
+    pub keys: Vec<KeyConfig>,
+}
+
+#[derive(Debug, Error)]
+pub enum KeyStoreLoadError {
+    #[error("error reading key file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("error parsing key file: {0}")]
+    Parse(#[from] toml::de::Error),
+}
+
+impl KeyStoreConfig {
+    pub fn load_from_file(path: &Path) -> Result<Self, KeyStoreLoadError> {
+        let contents = std::fs::read_to_string(path)?;
+        let config = toml::from_str(&contents)?;
+
+        return Ok(config);
+    }
+}
+
+/// Validates bearer/`?key=` tokens presented by clients against the
+/// configured keys, checking scope and the `not_before`/`not_after` window.
+#[derive(Default)]
+pub struct KeyStore {
+    keys: HashMap<String, KeyConfig>,
+}
+
+impl KeyStore {
+    pub fn new(config: KeyStoreConfig) -> Self {
+        let keys = config
+            .keys
+            .into_iter()
+            .map(|key| (key.token.clone(), key))
+            .collect();
+
+        return Self { keys };
+    }
+
+    fn validate(&self, token: &str, required: Scope) -> Result<(), AppError> {
+        let key = self.keys.get(token).ok_or(AppError::Unauthorized)?;
+
+        let now = Utc::now();
+        if let Some(not_before) = key.not_before {
+            if now < not_before {
+                return Err(AppError::Forbidden("key is not yet valid".to_string()));
+            }
+        }
+        if let Some(not_after) = key.not_after {
+            if now > not_after {
+                return Err(AppError::Forbidden("key has expired".to_string()));
+            }
+        }
+
+        if required == Scope::Controller && key.scope != Scope::Controller {
+            return Err(AppError::Forbidden(
+                "key does not have controller scope".to_string(),
+            ));
+        }
+
+        return Ok(());
+    }
+}
+
+fn token_from_parts(parts: &Parts) -> Result<String, AppError> {
+    if let Some(header) = parts.headers.get(axum::http::header::AUTHORIZATION) {
+        if let Some(token) = header
+            .to_str()
+            .ok()
+            .and_then(|value| value.strip_prefix("Bearer "))
+        {
+            return Ok(token.to_string());
+        }
+    }
+
+    if let Some(query) = parts.uri.query() {
+        if let Some((_, token)) =
+            form_urlencoded::parse(query.as_bytes()).find(|(key, _)| key == "key")
+        {
+            return Ok(token.to_string());
+        }
+    }
+
+    return Err(AppError::Unauthorized);
+}
+
+/// Extractor proving the request carries a key with read/viewer scope.
+pub struct ViewerKey;
+
+/// Extractor proving the request carries a key with controller scope.
+pub struct ControllerKey;
+
+#[axum::async_trait]
+impl<S> FromRequestParts<S> for ViewerKey
+where
+    S: Send + Sync,
+    std::sync::Arc<KeyStore>: FromRef<S>,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let key_store = std::sync::Arc::<KeyStore>::from_ref(state);
+        key_store.validate(&token_from_parts(parts)?, Scope::Viewer)?;
+
+        return Ok(ViewerKey);
+    }
+}
+
+#[axum::async_trait]
+impl<S> FromRequestParts<S> for ControllerKey
+where
+    S: Send + Sync,
+    std::sync::Arc<KeyStore>: FromRef<S>,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let key_store = std::sync::Arc::<KeyStore>::from_ref(state);
+        key_store.validate(&token_from_parts(parts)?, Scope::Controller)?;
+
+        return Ok(ControllerKey);
+    }
+}
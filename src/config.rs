@@ -0,0 +1,72 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+/// Env var pointing at a config file, checked when no path is given on the
+/// command line.
+pub const CONFIG_PATH_ENV_VAR: &str = "SONG_DIRECTOR_CONFIG";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub bind_ip: IpAddr,
+    pub port: u16,
+    pub templates_dir: PathBuf,
+    pub static_dir: PathBuf,
+    pub log_filter: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        return Self {
+            bind_ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 3000,
+            templates_dir: PathBuf::from("templates"),
+            static_dir: PathBuf::from("public"),
+            log_filter: "info,tower_http=info".to_string(),
+        };
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigLoadError {
+    #[error("error reading config file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("error parsing config file: {0}")]
+    Parse(#[from] toml::de::Error),
+}
+
+impl Config {
+    /// Loads config from the path given as the first CLI argument, falling
+    /// back to `SONG_DIRECTOR_CONFIG`, falling back to defaults if neither
+    /// is set. This lets the binary run unconfigured in development while
+    /// still being deployable without a recompile.
+    pub fn load() -> Result<Self, ConfigLoadError> {
+        let path = std::env::args()
+            .nth(1)
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os(CONFIG_PATH_ENV_VAR).map(PathBuf::from));
+
+        return match path {
+            Some(path) => Self::load_from_file(&path),
+            None => Ok(Self::default()),
+        };
+    }
+
+    pub fn load_from_file(path: &Path) -> Result<Self, ConfigLoadError> {
+        let contents = std::fs::read_to_string(path)?;
+        let config = toml::from_str(&contents)?;
+
+        return Ok(config);
+    }
+
+    pub fn templates_glob(&self) -> String {
+        return format!("{}/**/*.html", self.templates_dir.display());
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        return SocketAddr::new(self.bind_ip, self.port);
+    }
+}
@@ -2,24 +2,42 @@ use axum::response::IntoResponse;
 use hyper::StatusCode;
 use thiserror::Error;
 
+use crate::auth::KeyStoreLoadError;
+use crate::config::ConfigLoadError;
+
 #[derive(Debug, Error)]
 pub enum InitError {
     #[error("error reading templates: {0}")]
     TemplatesError(#[from] tera::Error),
     #[error("error starting web server: {0}")]
     WebServerError(#[from] hyper::Error),
+    #[error("error loading key file: {0}")]
+    KeyStoreError(#[from] KeyStoreLoadError),
+    #[error("error watching templates directory: {0}")]
+    TemplateWatchError(#[from] notify::Error),
+    #[error("error loading config file: {0}")]
+    ConfigError(#[from] ConfigLoadError),
 }
 
 #[derive(Debug, Error)]
 pub enum AppError {
     #[error("error rendering template: {0}")]
     TemplateError(#[from] tera::Error),
+    #[error("unauthorized")]
+    Unauthorized,
+    #[error("forbidden: {0}")]
+    Forbidden(String),
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> axum::response::Response {
         let status = match &self {
-            Self::TemplateError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::TemplateError(_) => {
+                crate::metrics::record_render_error();
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
         };
 
         return (status, self.to_string()).into_response();
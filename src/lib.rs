@@ -0,0 +1,6 @@
+pub mod auth;
+pub mod config;
+pub mod errors;
+pub mod metrics;
+pub mod rooms;
+pub mod template_reload;
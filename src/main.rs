@@ -1,29 +1,59 @@
-use std::{net::SocketAddr, num::NonZeroUsize};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
 
 use axum::{
     extract::{
         ws::{self, WebSocket},
-        ConnectInfo, State, WebSocketUpgrade,
+        ConnectInfo, FromRef, Path, State, WebSocketUpgrade,
     },
     response::{Html, IntoResponse},
     routing::{delete, get, put},
     Form, Router,
 };
 use hyper::StatusCode;
+use metrics_exporter_prometheus::PrometheusHandle;
 use serde::Deserialize;
+use song_director_htmx::auth::{ControllerKey, KeyStore, KeyStoreConfig, ViewerKey};
+use song_director_htmx::config::Config;
 use song_director_htmx::errors::{AppError, InitError};
-use tokio::sync::watch as watch_channel;
+use song_director_htmx::metrics::{
+    install_recorder, metrics_handler, record_render_error, record_section_change,
+    RequestDurationLayer, ViewerGuard,
+};
+use song_director_htmx::rooms::{RoomRegistry, SectionTuple};
+use song_director_htmx::template_reload::spawn_template_watcher;
+use std::num::NonZeroUsize;
+use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
 use tower_http::services::ServeDir;
 use tracing::Instrument;
 use tracing_subscriber::{prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt};
 
-type SectionTuple = (Option<char>, Option<NonZeroUsize>);
+const ROOM_CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+const KEYS_FILE_ENV_VAR: &str = "SONG_DIRECTOR_KEYS_FILE";
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+const PONG_TIMEOUT: Duration = Duration::from_secs(60);
 
 #[derive(Clone)]
-struct AppState<'a> {
-    tera: tera::Tera,
-    section_tx: &'a watch_channel::Sender<SectionTuple>,
-    section_rx: watch_channel::Receiver<SectionTuple>,
+struct AppState {
+    tera: Arc<RwLock<tera::Tera>>,
+    rooms: Arc<RoomRegistry>,
+    key_store: Arc<KeyStore>,
+    shutdown: CancellationToken,
+    metrics_handle: PrometheusHandle,
+}
+
+impl FromRef<AppState> for Arc<KeyStore> {
+    fn from_ref(state: &AppState) -> Self {
+        return Arc::clone(&state.key_store);
+    }
+}
+
+impl FromRef<AppState> for PrometheusHandle {
+    fn from_ref(state: &AppState) -> Self {
+        return state.metrics_handle.clone();
+    }
 }
 
 #[derive(Deserialize)]
@@ -38,33 +68,62 @@ struct SectionNumber {
 
 #[tokio::main]
 async fn main() -> Result<(), InitError> {
+    let config = Config::load()?;
+
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "info,tower_http=info".into()),
+                .unwrap_or_else(|_| config.log_filter.clone().into()),
         )
         .with(tracing_subscriber::fmt::layer().with_ansi(false))
         .init();
 
-    let tera = tera::Tera::new("templates/**/*.html")?;
-    let (section_tx, section_rx) = watch_channel::channel((None, None));
+    let tera = Arc::new(RwLock::new(tera::Tera::new(&config.templates_glob())?));
+    let rooms = Arc::new(RoomRegistry::new());
+    let _template_watcher =
+        spawn_template_watcher(Arc::clone(&tera), Arc::clone(&rooms), &config.templates_dir)
+            .map_err(InitError::from)?;
+    let key_store = Arc::new(match std::env::var_os(KEYS_FILE_ENV_VAR) {
+        Some(path) => KeyStore::new(KeyStoreConfig::load_from_file(path.as_ref())?),
+        None => {
+            tracing::warn!(
+                "{} not set; no keys configured, all controller/viewer routes will reject",
+                KEYS_FILE_ENV_VAR
+            );
+            KeyStore::default()
+        }
+    });
+    let shutdown = CancellationToken::new();
+    let metrics_handle = install_recorder();
     let app_state = AppState {
         tera,
-        section_tx: Box::leak(Box::new(section_tx)),
-        section_rx,
+        rooms: Arc::clone(&rooms),
+        key_store,
+        shutdown: shutdown.clone(),
+        metrics_handle,
     };
 
-    let file_service = ServeDir::new("public").precompressed_br();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(ROOM_CLEANUP_INTERVAL);
+        loop {
+            interval.tick().await;
+            rooms.cleanup_idle();
+        }
+    });
+
+    let file_service = ServeDir::new(&config.static_dir).precompressed_br();
     let app = Router::new()
-        .route("/", get(controller))
-        .route("/view", get(view))
-        .route("/section/type", put(set_section_type))
-        .route("/section/number", put(set_section_number))
-        .route("/section", delete(clear_section))
-        .route("/section", get(section_ws_handler))
+        .route("/:room", get(controller))
+        .route("/:room/view", get(view))
+        .route("/:room/section/type", put(set_section_type))
+        .route("/:room/section/number", put(set_section_number))
+        .route("/:room/section", delete(clear_section))
+        .route("/:room/section", get(section_ws_handler))
+        .route("/metrics", get(metrics_handler))
+        .layer(RequestDurationLayer)
         .with_state(app_state)
         .fallback_service(file_service);
-    let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
+    let addr = config.addr();
 
     tracing::info!(
         "song director server v{} listening on http://{}",
@@ -73,11 +132,42 @@ async fn main() -> Result<(), InitError> {
     );
     axum::Server::bind(&addr)
         .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(wait_for_shutdown_signal(shutdown))
         .await?;
 
     return Ok(());
 }
 
+/// Resolves once Ctrl+C or SIGTERM is received, and cancels `shutdown` so
+/// that in-flight WebSocket sockets can close cleanly before the runtime
+/// exits.
+async fn wait_for_shutdown_signal(shutdown: CancellationToken) {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    tracing::info!("Shutdown signal received, closing sockets");
+    shutdown.cancel();
+}
+
 fn section_segments_to_string(segments: &SectionTuple) -> String {
     if let Some(sec) = segments.0 {
         if let Some(num) = segments.1 {
@@ -92,84 +182,155 @@ fn section_segments_to_string(segments: &SectionTuple) -> String {
 }
 
 async fn controller(
-    State(AppState {
-        tera, section_rx, ..
-    }): State<AppState<'_>>,
+    _key: ControllerKey,
+    Path(room): Path<String>,
+    State(AppState { tera, rooms, .. }): State<AppState>,
 ) -> Result<Html<String>, AppError> {
-    let section = section_rx.borrow();
+    let section = rooms.receiver(&room).borrow().to_owned();
     let mut context = tera::Context::new();
     context.insert("song_section", &section_segments_to_string(&section));
 
-    return Ok(Html(tera.render("controller.html", &context)?));
+    return Ok(Html(tera.read().await.render("controller.html", &context)?));
 }
 
 async fn view(
-    State(AppState {
-        tera, section_rx, ..
-    }): State<AppState<'_>>,
+    _key: ViewerKey,
+    Path(room): Path<String>,
+    State(AppState { tera, rooms, .. }): State<AppState>,
 ) -> Result<Html<String>, AppError> {
-    let section = section_rx.borrow();
+    let section = rooms.receiver(&room).borrow().to_owned();
     let mut context = tera::Context::new();
     context.insert("song_section", &section_segments_to_string(&section));
 
-    return Ok(Html(tera.render("viewer.html", &context)?));
+    return Ok(Html(tera.read().await.render("viewer.html", &context)?));
 }
 
 async fn set_section_type(
-    State(AppState { section_tx, .. }): State<AppState<'_>>,
+    _key: ControllerKey,
+    Path(room): Path<String>,
+    State(AppState { rooms, .. }): State<AppState>,
     Form(SectionType { section_type }): Form<SectionType>,
 ) -> StatusCode {
-    tracing::debug!("Setting section type to {}", section_type);
-    section_tx.send_replace((Some(section_type), None));
+    tracing::debug!("Setting section type to {} in room {}", section_type, room);
+    rooms.sender(&room).send_replace((Some(section_type), None));
+    record_section_change();
 
     return StatusCode::NO_CONTENT;
 }
 
 async fn set_section_number(
-    State(AppState { section_tx, .. }): State<AppState<'_>>,
+    _key: ControllerKey,
+    Path(room): Path<String>,
+    State(AppState { rooms, .. }): State<AppState>,
     Form(SectionNumber { section_number }): Form<SectionNumber>,
 ) -> StatusCode {
-    tracing::debug!("Setting section number to {}", section_number);
-    section_tx.send_modify(|val| val.1 = Some(section_number));
+    tracing::debug!(
+        "Setting section number to {} in room {}",
+        section_number,
+        room
+    );
+    rooms
+        .sender(&room)
+        .send_modify(|val| val.1 = Some(section_number));
+    record_section_change();
 
     return StatusCode::NO_CONTENT;
 }
 
-async fn clear_section(State(AppState { section_tx, .. }): State<AppState<'_>>) -> StatusCode {
-    tracing::debug!("Clearing section");
-    section_tx.send_replace((None, None));
+async fn clear_section(
+    _key: ControllerKey,
+    Path(room): Path<String>,
+    State(AppState { rooms, .. }): State<AppState>,
+) -> StatusCode {
+    tracing::debug!("Clearing section in room {}", room);
+    rooms.sender(&room).send_replace((None, None));
+    record_section_change();
 
     return StatusCode::NO_CONTENT;
 }
 
 async fn section_ws_handler(
+    _key: ViewerKey,
     ws: WebSocketUpgrade,
+    Path(room): Path<String>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     State(AppState {
-        tera, section_rx, ..
-    }): State<AppState<'_>>,
+        tera,
+        rooms,
+        shutdown,
+        ..
+    }): State<AppState>,
 ) -> impl IntoResponse {
+    let section_rx = rooms.receiver(&room);
     return ws.on_upgrade(move |socket| {
-        section_socket(socket, tera, section_rx).instrument(tracing::info_span!(
+        section_socket(
+            socket,
+            tera,
+            section_rx,
+            shutdown,
+            PING_INTERVAL,
+            PONG_TIMEOUT,
+        )
+        .instrument(tracing::info_span!(
             "section_socket",
-            client_addr = addr.to_string()
+            client_addr = addr.to_string(),
+            room = room
         ))
     });
 }
 
 async fn section_socket(
     mut socket: WebSocket,
-    tera: tera::Tera,
-    mut section_rx: watch_channel::Receiver<SectionTuple>,
+    tera: Arc<RwLock<tera::Tera>>,
+    mut section_rx: tokio::sync::watch::Receiver<SectionTuple>,
+    shutdown: CancellationToken,
+    ping_interval: Duration,
+    pong_timeout: Duration,
 ) {
     tracing::info!("Socket connection established");
+    let _viewer_guard = ViewerGuard::new();
+    let mut ping_interval = tokio::time::interval(ping_interval);
+    let mut last_activity = tokio::time::Instant::now();
     loop {
         tokio::select! {
             biased;
-            Some(Ok(ws::Message::Close(_))) = socket.recv() => {
-                tracing::info!("Client closed socket");
+            Some(Ok(msg)) = socket.recv() => {
+                match msg {
+                    ws::Message::Close(_) => {
+                        tracing::info!("Client closed socket");
+                        return;
+                    }
+                    ws::Message::Ping(payload) => {
+                        last_activity = tokio::time::Instant::now();
+                        if let Err(err) = socket.send(ws::Message::Pong(payload)).await {
+                            tracing::warn!("Error sending pong: {}", err);
+                            return;
+                        }
+                    }
+                    ws::Message::Pong(_) => {
+                        last_activity = tokio::time::Instant::now();
+                    }
+                    _ => {
+                        last_activity = tokio::time::Instant::now();
+                    }
+                }
+            },
+            () = shutdown.cancelled() => {
+                tracing::info!("Server shutting down, closing socket");
+                let _ = socket.send(ws::Message::Close(None)).await;
                 return;
             },
+            _ = ping_interval.tick() => {
+                if last_activity.elapsed() > pong_timeout {
+                    tracing::warn!("No pong received within timeout, closing socket");
+                    let _ = socket.send(ws::Message::Close(None)).await;
+                    return;
+                }
+                if let Err(err) = socket.send(ws::Message::Ping(Vec::new())).await {
+                    tracing::warn!("Error sending ping: {}", err);
+                    return;
+                }
+            },
             changed = section_rx.changed() =>
             if changed.is_ok() {
                 let mut context = tera::Context::new();
@@ -177,7 +338,7 @@ async fn section_socket(
                     "song_section",
                     &section_segments_to_string(&section_rx.borrow()),
                 );
-                match tera.render("fragments/section-display.html", &context) {
+                match tera.read().await.render("fragments/section-display.html", &context) {
                     Ok(message_text) => {
                         tracing::debug!("Sending {}", message_text);
                         if let Err(err) = socket.send(ws::Message::Text(message_text)).await {
@@ -188,6 +349,7 @@ async fn section_socket(
                     }
                     Err(err) => {
                         tracing::error!("Error rendering template: {}", err);
+                        record_render_error();
                         tracing::info!("Closing socket");
                         return;
                     }
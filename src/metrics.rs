@@ -0,0 +1,102 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use axum::extract::State;
+use axum::http::{Request, Response};
+use axum::response::IntoResponse;
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use tower::{Layer, Service};
+
+const VIEWER_GAUGE: &str = "song_director_connected_viewers";
+const SECTION_CHANGES_COUNTER: &str = "song_director_section_changes_total";
+const RENDER_ERRORS_COUNTER: &str = "song_director_template_render_errors_total";
+const REQUEST_DURATION_HISTOGRAM: &str = "song_director_http_request_duration_seconds";
+
+/// Installs the process-wide Prometheus recorder and returns a handle that
+/// can render the current snapshot for the `/metrics` route.
+pub fn install_recorder() -> PrometheusHandle {
+    return PrometheusBuilder::new()
+        .install_recorder()
+        .expect("failed to install Prometheus recorder");
+}
+
+pub async fn metrics_handler(State(handle): State<PrometheusHandle>) -> impl IntoResponse {
+    return handle.render();
+}
+
+pub fn record_section_change() {
+    metrics::counter!(SECTION_CHANGES_COUNTER).increment(1);
+}
+
+pub fn record_render_error() {
+    metrics::counter!(RENDER_ERRORS_COUNTER).increment(1);
+}
+
+/// Increments the connected-viewer gauge on creation and decrements it on
+/// drop. Hold one of these for the lifetime of a `section_socket` call so
+/// every early-return branch (error, timeout, shutdown, client close)
+/// releases its count.
+pub struct ViewerGuard;
+
+#[allow(clippy::new_without_default)]
+impl ViewerGuard {
+    #[must_use]
+    pub fn new() -> Self {
+        metrics::gauge!(VIEWER_GAUGE).increment(1.0);
+
+        return Self;
+    }
+}
+
+impl Drop for ViewerGuard {
+    fn drop(&mut self) {
+        metrics::gauge!(VIEWER_GAUGE).decrement(1.0);
+    }
+}
+
+/// Tower layer recording a histogram of HTTP request durations.
+#[derive(Clone, Default)]
+pub struct RequestDurationLayer;
+
+impl<S> Layer<S> for RequestDurationLayer {
+    type Service = RequestDurationService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return RequestDurationService { inner };
+    }
+}
+
+#[derive(Clone)]
+pub struct RequestDurationService<S> {
+    inner: S,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for RequestDurationService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    ReqBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let start = Instant::now();
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        return Box::pin(async move {
+            let response = inner.call(req).await;
+            metrics::histogram!(REQUEST_DURATION_HISTOGRAM).record(start.elapsed().as_secs_f64());
+
+            return response;
+        });
+    }
+}
@@ -0,0 +1,71 @@
+use std::num::NonZeroUsize;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use tokio::sync::watch;
+
+pub type SectionTuple = (Option<char>, Option<NonZeroUsize>);
+
+/// How long since a room was last accessed (through `sender` or `receiver`)
+/// before the cleanup sweep drops its channel, provided it has no
+/// subscribers at that moment.
+const ROOM_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+struct Room {
+    tx: watch::Sender<SectionTuple>,
+    last_touched: Instant,
+}
+
+/// One `watch` channel per room, keyed by room id and created lazily on
+/// first access, so independent venues/stages don't share a section.
+#[derive(Default)]
+pub struct RoomRegistry {
+    rooms: DashMap<String, Room>,
+}
+
+impl RoomRegistry {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Returns the room's sender, creating its channel if this is the first
+    /// time the room has been touched.
+    pub fn sender(&self, room: &str) -> watch::Sender<SectionTuple> {
+        let mut entry = self.rooms.entry(room.to_string()).or_insert_with(|| Room {
+            tx: watch::channel((None, None)).0,
+            last_touched: Instant::now(),
+        });
+        entry.last_touched = Instant::now();
+
+        return entry.tx.clone();
+    }
+
+    /// Returns a receiver for the room, creating its channel if needed.
+    pub fn receiver(&self, room: &str) -> watch::Receiver<SectionTuple> {
+        return self.sender(room).subscribe();
+    }
+
+    /// Re-sends the current value on every room's channel, e.g. to make
+    /// connected viewers re-render after a template reload.
+    pub fn touch_all(&self) {
+        for room in self.rooms.iter() {
+            room.tx.send_modify(|_| {});
+        }
+    }
+
+    /// Drops rooms that have no subscribers and have not been accessed for
+    /// `ROOM_IDLE_TIMEOUT`, so venues that wrap up for the night don't leak
+    /// channels forever.
+    pub fn cleanup_idle(&self) {
+        let before = self.rooms.len();
+        self.rooms.retain(|_, room| {
+            let idle = room.last_touched.elapsed() > ROOM_IDLE_TIMEOUT;
+            let unused = room.tx.receiver_count() == 0;
+            return !(idle && unused);
+        });
+        let removed = before - self.rooms.len();
+        if removed > 0 {
+            tracing::debug!("Cleaned up {} idle room(s)", removed);
+        }
+    }
+}
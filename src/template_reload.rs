@@ -0,0 +1,51 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::RwLock;
+
+use crate::rooms::RoomRegistry;
+
+/// Watches `dir` for changes and reloads `tera` in place, so editing
+/// `controller.html`, `viewer.html`, or a fragment takes effect without
+/// restarting the server. The returned watcher must be kept alive for the
+/// duration of the watch.
+pub fn spawn_template_watcher(
+    tera: Arc<RwLock<tera::Tera>>,
+    rooms: Arc<RoomRegistry>,
+    dir: impl AsRef<Path>,
+) -> notify::Result<RecommendedWatcher> {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if let Ok(event) = event {
+            let _ = tx.send(event);
+        }
+    })?;
+    watcher.watch(dir.as_ref(), RecursiveMode::Recursive)?;
+
+    tokio::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            if event.kind.is_modify() || event.kind.is_create() || event.kind.is_remove() {
+                reload(&tera, &rooms).await;
+            }
+        }
+    });
+
+    return Ok(watcher);
+}
+
+async fn reload(tera: &Arc<RwLock<tera::Tera>>, rooms: &RoomRegistry) {
+    let mut guard = tera.write().await;
+    match guard.full_reload() {
+        Ok(()) => {
+            tracing::info!("Templates reloaded");
+            drop(guard);
+            // Nudge connected viewers so they re-render against the new templates.
+            rooms.touch_all();
+        }
+        Err(err) => {
+            tracing::error!("Error reloading templates: {}", err);
+        }
+    }
+}